@@ -1,34 +1,60 @@
+use num_traits::Float;
+
 #[allow(non_camel_case_types)]
 pub type float = f64;
 
-pub type Point = (float, float);
-pub type Line = (Point, Point);
+pub type Point<C = float> = (C, C);
+pub type Line<C = float> = (Point<C>, Point<C>);
+
+pub trait HasPoint<C: Float = float>: Copy {
+    fn to_point(self) -> Point<C>;
+}
+
+impl<C: Float> HasPoint<C> for Point<C> {
+    fn to_point(self) -> Point<C> {self}
+}
 
-pub trait HasPoint: Copy {
-    fn to_point(self) -> Point;
+pub fn ramer_douglas_peucker<C: Float, T: HasPoint<C>>(v: Vec<T>, epsilon: C) -> Vec<T> {
+    ramer_douglas_peucker_with_metric(v, epsilon, Metric::Euclidean)
 }
 
-impl HasPoint for Point {
-    fn to_point(self) -> Point {self}
+/// Same as `ramer_douglas_peucker`, but lets the caller pick the distance
+/// backend. Use `Metric::Haversine` for (lon, lat) tracks in degrees, with
+/// `epsilon` in meters.
+pub fn ramer_douglas_peucker_with_metric<C: Float, T: HasPoint<C>>(v: Vec<T>, epsilon: C, metric: Metric) -> Vec<T> {
+    let indices = rdp_indices_with_metric(&v, epsilon, metric);
+    indices.into_iter().map(|i| v[i]).collect()
 }
 
-pub fn ramer_douglas_peucker<T: HasPoint>(v: Vec<T>, epsilon: float) -> Vec<T> {
+/// Runs Ramer-Douglas-Peucker over a slice without cloning or moving any
+/// `T`, returning the sorted indices of the points that survive
+/// simplification. Useful when `T` is expensive to move, or when the
+/// result needs to be used to index into parallel arrays (timestamps,
+/// metadata, ...) alongside the original points.
+pub fn rdp_indices<C: Float, T: HasPoint<C>>(v: &[T], epsilon: C) -> Vec<usize> {
+    rdp_indices_with_metric(v, epsilon, Metric::Euclidean)
+}
+
+/// Same as `rdp_indices`, but lets the caller pick the distance backend.
+/// Use `Metric::Haversine` for (lon, lat) tracks in degrees, with
+/// `epsilon` in meters.
+pub fn rdp_indices_with_metric<C: Float, T: HasPoint<C>>(v: &[T], epsilon: C, metric: Metric) -> Vec<usize> {
     let length = v.len();
     if length < 3 {
-        return v;
+        return (0..length).collect();
     }
     let mut stack = vec![(0, length - 1)];
     let mut result = Vec::new();
-    let mut last_stack_index = -1;
+    let mut last_stack_index: isize = -1;
 
     while let Some((start_index, end_index)) =  stack.pop() {
         // println!("start = {}, end = {}", start_index, end_index);
-        let mut max_distance = 0.0 as float;
+        let mut max_distance = C::zero();
         let mut max_index = start_index;
         for i in start_index+1..end_index {
             let point = v[i].to_point();
             let line = (v[start_index].to_point(), v[end_index].to_point());
-            let distance = distance_point_to_line(point, line);
+            let distance = distance_point_to_line_with_metric(point, line, metric);
             // println!("i = {}, distance = {}", i, distance);
             if distance > max_distance {
                 max_distance = distance;
@@ -39,34 +65,350 @@ pub fn ramer_douglas_peucker<T: HasPoint>(v: Vec<T>, epsilon: float) -> Vec<T> {
             stack.push((max_index, end_index));
             stack.push((start_index, max_index));
         } else {
-            if last_stack_index != start_index {
-                result.push(v[start_index]);
+            if last_stack_index != start_index as isize {
+                result.push(start_index);
             }
-            result.push(v[end_index]);
-            last_stack_index = end_index;
+            result.push(end_index);
+            last_stack_index = end_index as isize;
         }
     }
+    result.sort_unstable();
     result
 }
 
-pub fn distance_point_to_line(p: Point, l: Line) -> float {
-    if l.0 == l.1 {
-        return distance_point_to_point(p, l.0);
+pub fn ramer_douglas_peucker_closed<C: Float, T: HasPoint<C>>(v: Vec<T>, epsilon: C) -> Vec<T> {
+    let indices = rdp_indices_closed(&v, epsilon);
+    indices.into_iter().map(|i| v[i]).collect()
+}
+
+/// Closed-ring variant of `rdp_indices`. Plain RDP treats its input as an
+/// open polyline, which simplifies a closed ring (first point == last
+/// point, as in a polygon boundary) poorly: there is no natural
+/// "farthest" anchor, since the start and end of the line are the same
+/// point. Instead, this finds the point farthest from the start vertex,
+/// splits the ring into two open chains at that point, runs `rdp_indices`
+/// on each independently, and stitches the results back together with
+/// the shared vertex deduplicated. The returned indices still describe a
+/// closed ring: they start at `0` and end at `v.len() - 1`, whose point
+/// is the same as the first.
+///
+/// If `v` isn't actually closed (first and last points differ), this
+/// just falls back to `rdp_indices`.
+pub fn rdp_indices_closed<C: Float, T: HasPoint<C>>(v: &[T], epsilon: C) -> Vec<usize> {
+    let length = v.len();
+    if length < 3 || v[0].to_point() != v[length - 1].to_point() {
+        return rdp_indices(v, epsilon);
     }
-    let a = (l.0).1 - (l.1).1;
-    let b = (l.1).0 - (l.0).0;
-    let c = (l.0).0 * (l.1).1 - (l.1).0 * (l.0).1;
-    let result = (a * p.0 + b * p.1 + c) / (a * a + b * b).sqrt();
-    result.abs()
+
+    let start = v[0].to_point();
+    let mut far_index = 1;
+    let mut far_distance = C::zero();
+    for (i, point) in v.iter().enumerate().take(length - 1).skip(1) {
+        let distance = distance_point_to_point(point.to_point(), start);
+        if distance > far_distance {
+            far_distance = distance;
+            far_index = i;
+        }
+    }
+
+    let first_chain_indices = rdp_indices(&v[0..=far_index], epsilon);
+    let second_chain_indices = rdp_indices(&v[far_index..length], epsilon);
+
+    let mut result = first_chain_indices;
+    let mut second_chain_indices: Vec<usize> = second_chain_indices.into_iter().map(|i| i + far_index).collect();
+    if second_chain_indices.first() == result.last() {
+        second_chain_indices.remove(0);
+    }
+    result.append(&mut second_chain_indices);
+    result
+}
+
+/// Distance backend used to judge how far a point sits from a line.
+/// `Euclidean` treats points as a flat Cartesian plane; `Haversine`
+/// treats them as (lon, lat) in degrees on the Earth's surface and
+/// measures cross-track distance in meters, which is what geographic
+/// (GPS) tracks need.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Metric {
+    Euclidean,
+    Haversine,
+}
+
+/// Mean Earth radius in meters, as used by the haversine formulas below.
+pub const EARTH_RADIUS_METERS: float = 6_371_000.0;
+
+fn earth_radius_meters<C: Float>() -> C {
+    C::from(EARTH_RADIUS_METERS).unwrap()
+}
+
+pub fn distance_point_to_line<C: Float>(p: Point<C>, l: Line<C>) -> C {
+    distance_point_to_line_with_metric(p, l, Metric::Euclidean)
 }
 
-pub fn distance_point_to_point(x: Point, y: Point) -> float {
+pub fn distance_point_to_line_with_metric<C: Float>(p: Point<C>, l: Line<C>, metric: Metric) -> C {
+    match metric {
+        Metric::Euclidean => {
+            if l.0 == l.1 {
+                return distance_point_to_point(p, l.0);
+            }
+            let a = (l.0).1 - (l.1).1;
+            let b = (l.1).0 - (l.0).0;
+            let c = (l.0).0 * (l.1).1 - (l.1).0 * (l.0).1;
+            let result = (a * p.0 + b * p.1 + c) / (a * a + b * b).sqrt();
+            result.abs()
+        }
+        Metric::Haversine => cross_track_distance(p, l),
+    }
+}
+
+pub fn distance_point_to_point<C: Float>(x: Point<C>, y: Point<C>) -> C {
     let a = y.0 - x.0;
     let b = y.1 - x.1;
     let result = (a * a + b * b).sqrt();
     result.abs()
 }
 
+/// Great-circle distance in meters between two (lon, lat) points given in
+/// degrees, via the haversine formula.
+pub fn haversine_distance<C: Float>(x: Point<C>, y: Point<C>) -> C {
+    let two = C::from(2.0).unwrap();
+    let (lon1, lat1) = (x.0.to_radians(), x.1.to_radians());
+    let (lon2, lat2) = (y.0.to_radians(), y.1.to_radians());
+    let dlat = lat2 - lat1;
+    let dlon = lon2 - lon1;
+    let a = (dlat / two).sin().powi(2) + lat1.cos() * lat2.cos() * (dlon / two).sin().powi(2);
+    earth_radius_meters::<C>() * two * a.sqrt().asin()
+}
+
+/// Initial bearing in radians for the great-circle path from `x` to `y`,
+/// both given as (lon, lat) in degrees.
+fn bearing<C: Float>(x: Point<C>, y: Point<C>) -> C {
+    let (lon1, lat1) = (x.0.to_radians(), x.1.to_radians());
+    let (lon2, lat2) = (y.0.to_radians(), y.1.to_radians());
+    let dlon = lon2 - lon1;
+    let y_component = dlon.sin() * lat2.cos();
+    let x_component = lat1.cos() * lat2.sin() - lat1.sin() * lat2.cos() * dlon.cos();
+    y_component.atan2(x_component)
+}
+
+/// Cross-track distance in meters from `p` to the great-circle segment
+/// `l`, both given as (lon, lat) in degrees.
+fn cross_track_distance<C: Float>(p: Point<C>, l: Line<C>) -> C {
+    if l.0 == l.1 {
+        return haversine_distance(p, l.0);
+    }
+    let r = earth_radius_meters::<C>();
+    let angular_distance_start_to_point = haversine_distance(l.0, p) / r;
+    let bearing_start_to_point = bearing(l.0, p);
+    let bearing_start_to_end = bearing(l.0, l.1);
+    let result = (angular_distance_start_to_point.sin() * (bearing_start_to_point - bearing_start_to_end).sin()).asin();
+    result.abs() * r
+}
+
+fn triangle_area<C: Float>(a: Point<C>, b: Point<C>, c: Point<C>) -> C {
+    let result = (b.0 - a.0) * (c.1 - a.1) - (c.0 - a.0) * (b.1 - a.1);
+    C::from(0.5).unwrap() * result.abs()
+}
+
+/// Entry in the area min-heap used by `visvalingam_whyatt`. Ordered by
+/// `area` only, smallest first (via `Reverse`); `index` identifies the
+/// point in the original slice so a popped entry can be checked against
+/// the point's current effective area and discarded if stale.
+struct AreaEntry<C> {
+    area: C,
+    index: usize,
+}
+
+impl<C: Float> PartialEq for AreaEntry<C> {
+    fn eq(&self, other: &Self) -> bool {
+        self.area == other.area
+    }
+}
+
+impl<C: Float> Eq for AreaEntry<C> {}
+
+impl<C: Float> PartialOrd for AreaEntry<C> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<C: Float> Ord for AreaEntry<C> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.area.partial_cmp(&other.area).unwrap_or(std::cmp::Ordering::Equal)
+    }
+}
+
+/// Visvalingam-Whyatt area-based simplification. Returns the sorted
+/// indices that survive, analogous to `rdp_indices` but judging each
+/// point by the area of the triangle it forms with its current
+/// neighbors rather than perpendicular distance. Repeatedly removes the
+/// point with the smallest effective area until the smallest remaining
+/// area is at least `min_area`; the first and last points are never
+/// removed.
+pub fn visvalingam_whyatt<C: Float, T: HasPoint<C>>(v: &[T], min_area: C) -> Vec<usize> {
+    let length = v.len();
+    if length < 3 {
+        return (0..length).collect();
+    }
+
+    let mut prev: Vec<usize> = (0..length).collect();
+    let mut next: Vec<usize> = (0..length).collect();
+    for i in 0..length {
+        prev[i] = if i == 0 { 0 } else { i - 1 };
+        next[i] = if i == length - 1 { length - 1 } else { i + 1 };
+    }
+
+    let mut area = vec![C::infinity(); length];
+    let mut heap = std::collections::BinaryHeap::new();
+    for i in 1..length - 1 {
+        area[i] = triangle_area(v[i - 1].to_point(), v[i].to_point(), v[i + 1].to_point());
+        heap.push(std::cmp::Reverse(AreaEntry { area: area[i], index: i }));
+    }
+
+    let mut removed = vec![false; length];
+    while let Some(std::cmp::Reverse(entry)) = heap.pop() {
+        if removed[entry.index] || entry.area != area[entry.index] {
+            continue;
+        }
+        if entry.area >= min_area {
+            break;
+        }
+
+        let index = entry.index;
+        removed[index] = true;
+        let p = prev[index];
+        let n = next[index];
+        next[p] = n;
+        prev[n] = p;
+
+        if p != 0 {
+            area[p] = triangle_area(v[prev[p]].to_point(), v[p].to_point(), v[n].to_point());
+            heap.push(std::cmp::Reverse(AreaEntry { area: area[p], index: p }));
+        }
+        if n != length - 1 {
+            area[n] = triangle_area(v[p].to_point(), v[n].to_point(), v[next[n]].to_point());
+            heap.push(std::cmp::Reverse(AreaEntry { area: area[n], index: n }));
+        }
+    }
+
+    let mut result = Vec::new();
+    let mut index = 0;
+    loop {
+        result.push(index);
+        if index == length - 1 {
+            break;
+        }
+        index = next[index];
+    }
+    result
+}
+
+fn orientation<C: Float>(p: Point<C>, q: Point<C>, r: Point<C>) -> i32 {
+    let val = (q.1 - p.1) * (r.0 - q.0) - (q.0 - p.0) * (r.1 - q.1);
+    if val == C::zero() {
+        0
+    } else if val > C::zero() {
+        1
+    } else {
+        2
+    }
+}
+
+/// Whether `q` lies within the bounding box of `p` and `r`. Only
+/// meaningful when `p`, `q`, `r` are already known to be collinear.
+fn on_segment<C: Float>(p: Point<C>, q: Point<C>, r: Point<C>) -> bool {
+    q.0 <= p.0.max(r.0) && q.0 >= p.0.min(r.0) && q.1 <= p.1.max(r.1) && q.1 >= p.1.min(r.1)
+}
+
+fn segments_intersect<C: Float>(l1: Line<C>, l2: Line<C>) -> bool {
+    let (p1, q1) = l1;
+    let (p2, q2) = l2;
+    let o1 = orientation(p1, q1, p2);
+    let o2 = orientation(p1, q1, q2);
+    let o3 = orientation(p2, q2, p1);
+    let o4 = orientation(p2, q2, q1);
+
+    if o1 != o2 && o3 != o4 {
+        return true;
+    }
+    if o1 == 0 && on_segment(p1, p2, q1) {
+        return true;
+    }
+    if o2 == 0 && on_segment(p1, q2, q1) {
+        return true;
+    }
+    if o3 == 0 && on_segment(p2, p1, q2) {
+        return true;
+    }
+    if o4 == 0 && on_segment(p2, q1, q2) {
+        return true;
+    }
+    false
+}
+
+/// Whether `candidate` crosses any of the already-accepted `segments`,
+/// other than the one it is joined to (the last one accepted, which
+/// shares `candidate`'s start point and is not a real crossing).
+fn crosses_accepted<C: Float>(candidate: Line<C>, segments: &[Line<C>]) -> bool {
+    match segments.len().checked_sub(1) {
+        Some(without_adjacent) => segments[..without_adjacent].iter().any(|s| segments_intersect(candidate, *s)),
+        None => false,
+    }
+}
+
+/// Topology-preserving variant of `rdp_indices`. Standard RDP can
+/// simplify a non-self-intersecting polyline into one that crosses
+/// itself, which breaks rendering and GIS validity. This variant checks
+/// every candidate shortcut segment against the segments already
+/// accepted into the output and, if it would cross one of them, forces
+/// the max-distance split point to be kept instead of collapsing the
+/// range, even when that point's distance is below `epsilon`.
+///
+/// The crossing check is O(k) in the number of already-accepted
+/// segments per candidate, so this is O(n*k) overall -- intended for
+/// moderate-size polylines where validity matters more than raw speed.
+pub fn ramer_douglas_peucker_preserve<C: Float, T: HasPoint<C>>(v: &[T], epsilon: C) -> Vec<usize> {
+    let length = v.len();
+    if length < 3 {
+        return (0..length).collect();
+    }
+    let mut stack = vec![(0, length - 1)];
+    let mut result = Vec::new();
+    let mut segments: Vec<Line<C>> = Vec::new();
+    let mut last_stack_index: isize = -1;
+
+    while let Some((start_index, end_index)) = stack.pop() {
+        let mut max_distance = C::zero();
+        let mut max_index = start_index;
+        for i in start_index + 1..end_index {
+            let point = v[i].to_point();
+            let line = (v[start_index].to_point(), v[end_index].to_point());
+            let distance = distance_point_to_line(point, line);
+            if distance > max_distance {
+                max_distance = distance;
+                max_index = i;
+            }
+        }
+
+        let candidate = (v[start_index].to_point(), v[end_index].to_point());
+        let would_cross = max_index != start_index && crosses_accepted(candidate, &segments);
+        if max_distance > epsilon || would_cross {
+            stack.push((max_index, end_index));
+            stack.push((start_index, max_index));
+        } else {
+            if last_stack_index != start_index as isize {
+                result.push(start_index);
+            }
+            result.push(end_index);
+            segments.push(candidate);
+            last_stack_index = end_index as isize;
+        }
+    }
+    result.sort_unstable();
+    result
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -96,12 +438,94 @@ mod tests {
         assert_eq!(vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)],ramer_douglas_peucker(vec![(0.0,0.0),(1.0,0.0),(2.0,0.0),(2.0,1.0),(2.0,2.0),(1.0,2.0),(0.0,2.0),(0.0,1.0),(0.0, 0.0)], 1.0));
     }
 
+    #[test]
+    fn reduce_vector_f32() {
+        let points: Vec<(f32, f32)> = vec![(0.0, 2.0), (1.0, 1.0), (3.0, 0.0), (5.0, 1.0)];
+        assert_eq!(vec![(0.0, 2.0), (3.0, 0.0), (5.0, 1.0)], ramer_douglas_peucker(points, 0.5f32));
+    }
+
+    #[test]
+    fn reduce_indices() {
+        let points = vec![(0.0, 2.0), (1.0, 1.0), (3.0, 0.0), (5.0, 1.0)];
+        assert_eq!(vec![0, 1, 2, 3], rdp_indices(&points, 0.1));
+        assert_eq!(vec![0, 2, 3], rdp_indices(&points, 0.5));
+    }
+
+    #[test]
+    fn reduce_visvalingam_whyatt() {
+        let points = vec![(0.0, 0.0), (1.0, 1.0), (2.0, 0.0), (3.0, 3.0), (4.0, 0.0)];
+        assert_eq!(vec![0, 1, 2, 3, 4], visvalingam_whyatt(&points, 0.5));
+        assert_eq!(vec![0, 2, 3, 4], visvalingam_whyatt(&points, 2.5));
+        assert_eq!(vec![0, 4], visvalingam_whyatt(&points, 10.0));
+    }
+
     #[test]
     fn calculate_point_to_line_distance() {
         assert!((distance_point_to_line((3.0, 2.0), ((-2.0, 0.0),(0.0, 2.0))) - 2.12132).abs() < 0.00001);
         assert!(distance_point_to_line((0.0, 0.0), ((-1.0, 0.0),(1.0, 0.0))).abs() < 0.00001);
     }
 
+    #[test]
+    fn calculate_haversine_cross_track_distance() {
+        // ~1 degree of longitude along the equator is about 111.2 km.
+        let start = (0.0, 0.0);
+        let end = (2.0, 0.0);
+        let on_the_line = distance_point_to_line_with_metric((1.0, 0.0), (start, end), Metric::Haversine);
+        assert!(on_the_line.abs() < 1.0);
+
+        let off_the_line = distance_point_to_line_with_metric((1.0, 1.0), (start, end), Metric::Haversine);
+        assert!((off_the_line - 111195.0).abs() < 100.0);
+
+        // Degenerate segment falls back to plain haversine point-to-point distance.
+        let degenerate = distance_point_to_line_with_metric((1.0, 1.0), (start, start), Metric::Haversine);
+        assert!((degenerate - haversine_distance((1.0, 1.0), start)).abs() < 0.00001);
+    }
+
+    #[test]
+    fn reduce_vector_with_haversine_metric() {
+        let track = vec![(0.0, 0.0), (1.0, 0.0005), (2.0, 0.0)];
+        assert_eq!(vec![(0.0, 0.0), (2.0, 0.0)], ramer_douglas_peucker_with_metric(track.clone(), 1000.0, Metric::Haversine));
+        assert_eq!(track.clone(), ramer_douglas_peucker_with_metric(track, 1.0, Metric::Haversine));
+    }
+
+    #[test]
+    fn preserve_avoids_self_intersection() {
+        // A loop-shaped polyline where standard RDP drops a point whose
+        // absence makes the shortcut segment cross an earlier segment.
+        let points = vec![
+            (2.8, 2.9),
+            (4.5, 8.1),
+            (7.3, 8.3),
+            (9.1, 4.3),
+            (7.4, 0.1),
+            (0.3, 0.7),
+            (1.1, 5.2),
+        ];
+        assert_eq!(vec![0, 2, 4, 6], rdp_indices(&points, 4.5));
+        assert_eq!(vec![0, 2, 4, 5, 6], ramer_douglas_peucker_preserve(&points, 4.5));
+    }
+
+    #[test]
+    fn reduce_closed_ring() {
+        let square = vec![(0.0,0.0),(1.0,0.0),(2.0,0.0),(2.0,1.0),(2.0,2.0),(1.0,2.0),(0.0,2.0),(0.0,1.0),(0.0, 0.0)];
+        assert_eq!(vec![0, 2, 4, 6, 8], rdp_indices_closed(&square, 1.0));
+        assert_eq!(vec![0, 2, 4, 6, 8], rdp_indices(&square, 1.0));
+
+        // A ring whose first and last points aren't equal falls back to
+        // plain RDP.
+        let open = vec![(0.0, 0.0), (1.0, 5.0), (2.0, 0.0)];
+        assert_eq!(rdp_indices(&open, 0.1), rdp_indices_closed(&open, 0.1));
+    }
+
+    #[test]
+    fn reduce_vector_closed() {
+        let square = vec![(0.0,0.0),(1.0,0.0),(2.0,0.0),(2.0,1.0),(2.0,2.0),(1.0,2.0),(0.0,2.0),(0.0,1.0),(0.0, 0.0)];
+        assert_eq!(
+            vec![(0.0, 0.0), (2.0, 0.0), (2.0, 2.0), (0.0, 2.0), (0.0, 0.0)],
+            ramer_douglas_peucker_closed(square, 1.0),
+        );
+    }
+
     #[test]
     fn calculate_point_to_point_distance() {
         assert!((distance_point_to_point((3.0, 2.0), (5.0, -1.0)) - 3.60555).abs() < 0.00001);